@@ -0,0 +1,72 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use errors::DiagnosticBuilder;
+use hir::def_id::DefId;
+use traits::{ObligationCauseCode, PredicateObligation};
+use ty::{self, Ty, TyCtxt};
+
+/// Builds the "the trait bound `T: Trait` is not satisfied" error for a
+/// trait obligation that selection found unimplemented. This is the
+/// auto-trait selection failure path: an `Unimplemented` result for a
+/// `Send`/`Sync`/other auto-trait obligation is reported through here, and
+/// if the obligation's cause is `ObligationCauseCode::GeneratorInterior`
+/// (i.e. it exists to check a generator's saved state), the base error is
+/// augmented with a note blaming the concrete value responsible rather
+/// than leaving just the opaque witness type in the message.
+pub fn report_selection_error<'a, 'gcx, 'tcx>(
+    tcx: TyCtxt<'a, 'gcx, 'tcx>,
+    obligation: &PredicateObligation<'tcx>,
+) -> DiagnosticBuilder<'a> {
+    let trait_ref = match obligation.predicate {
+        ty::Predicate::Trait(ref data) => data.skip_binder().trait_ref,
+        _ => bug!("report_selection_error called with a non-trait predicate"),
+    };
+    let self_ty = trait_ref.self_ty();
+
+    let mut err = struct_span_err!(
+        tcx.sess,
+        obligation.cause.span,
+        E0277,
+        "the trait bound `{}: {}` is not satisfied",
+        self_ty,
+        tcx.item_path_str(trait_ref.def_id),
+    );
+
+    if let ObligationCauseCode::GeneratorInterior(generator_def_id) = obligation.cause.code {
+        note_obligation_cause_for_generator_interior(
+            tcx, &mut err, generator_def_id, trait_ref.def_id, self_ty,
+        );
+    }
+
+    err
+}
+
+/// If `ty` is one of the types saved in `generator_def_id`'s interior
+/// (`ty::TypeckTables::generator_interior_types`), adds a "value is held
+/// across this suspend point" note to `err` pointing at both where the
+/// value came from and the `yield` it is live across. `trait_def_id`
+/// names the specific auto trait that failed to hold, so the note reads
+/// correctly for `Sync` (or any other auto trait) and not just `Send`.
+fn note_obligation_cause_for_generator_interior<'a, 'b, 'gcx, 'tcx>(
+    tcx: TyCtxt<'a, 'gcx, 'tcx>,
+    err: &mut DiagnosticBuilder<'b>,
+    generator_def_id: DefId,
+    trait_def_id: DefId,
+    ty: Ty<'tcx>,
+) {
+    let tables = tcx.typeck_tables_of(generator_def_id);
+    if let Some(cause) = tables.generator_interior_types.get(&ty) {
+        err.span_note(cause.span,
+                       &format!("has type `{}` which does not implement `{}`",
+                                ty, tcx.item_path_str(trait_def_id)));
+        err.span_note(cause.yield_span, "the value is held across this suspend point");
+    }
+}
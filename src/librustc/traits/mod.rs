@@ -0,0 +1,51 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Trait obligation bookkeeping: what must hold (`predicate`), and why it
+//! was required in the first place (`cause`). Keeping the two separate is
+//! what lets `error_reporting` attach a specific explanation to a failed
+//! obligation instead of only reporting that some predicate didn't hold.
+
+pub mod error_reporting;
+
+use hir::def_id::DefId;
+use syntax_pos::Span;
+use ty;
+
+/// A predicate that must hold, and the reason it was required.
+#[derive(Clone)]
+pub struct Obligation<'tcx, T> {
+    pub cause: ObligationCause,
+    pub predicate: T,
+}
+
+pub type PredicateObligation<'tcx> = Obligation<'tcx, ty::Predicate<'tcx>>;
+
+#[derive(Clone)]
+pub struct ObligationCause {
+    pub span: Span,
+    pub code: ObligationCauseCode,
+}
+
+/// Why an obligation was required. Most obligations arise directly from
+/// source and carry no extra information (`MiscObligation`); some are
+/// synthesized by the compiler itself and need to be explained differently
+/// when they fail.
+#[derive(Clone)]
+pub enum ObligationCauseCode {
+    /// No special reason; the obligation arose directly from source.
+    MiscObligation,
+    /// This obligation checks that a generator's interior (its saved
+    /// state) satisfies an auto trait (`Send`, `Sync`, ...). Carries the
+    /// generator's `DefId` so a failure can be traced back to the
+    /// concrete value in `ty::TypeckTables::generator_interior_types`
+    /// that is actually responsible.
+    GeneratorInterior(DefId),
+}
@@ -0,0 +1,59 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use hir::def_id::DefId;
+use ty::{Ty, TyCtxt};
+use ty::subst::Substs;
+use ty::generator_interior::{GeneratorInterior, GeneratorInteriorTypeCause};
+use util::nodemap::{FxHashMap, NodeMap};
+use syntax::ast::NodeId;
+
+/// The result of type-checking a single body (a function, closure, const
+/// expression, or generator). One `TypeckTables` is shared by an item and
+/// all the closures and generators nested inside it: a closure or
+/// generator does not get its own independent tables.
+pub struct TypeckTables<'tcx> {
+    /// Stores the type for every node, indexed by the node's `NodeId`.
+    pub node_types: NodeMap<Ty<'tcx>>,
+
+    /// Stores the type parameters which were substituted to obtain the
+    /// type of each node.
+    pub node_substs: NodeMap<&'tcx Substs<'tcx>>,
+
+    /// For every generator nested in this item (including the item
+    /// itself, if it is a generator), the resolved interior computed by
+    /// `resolve_interior`, keyed by the generator's own `DefId`. Keying
+    /// by `DefId` (rather than storing a single value) is what lets a
+    /// nested generator's interior live alongside its enclosing item's in
+    /// the one shared table.
+    pub generator_interiors: FxHashMap<DefId, GeneratorInterior<'tcx>>,
+
+    /// For every interior type that could be blamed on a specific yield,
+    /// the span of the value and the span of the yield it is live
+    /// across. Trait selection looks this up by the offending type when
+    /// an auto-trait obligation on a generator's witness fails, to emit a
+    /// "value is held across this suspend point" note.
+    pub generator_interior_types: FxHashMap<Ty<'tcx>, GeneratorInteriorTypeCause>,
+}
+
+impl<'tcx> TypeckTables<'tcx> {
+    pub fn empty() -> Self {
+        TypeckTables {
+            node_types: NodeMap(),
+            node_substs: NodeMap(),
+            generator_interiors: FxHashMap(),
+            generator_interior_types: FxHashMap(),
+        }
+    }
+
+    pub fn node_id_to_type(&self, id: NodeId) -> Ty<'tcx> {
+        self.node_types[&id]
+    }
+}
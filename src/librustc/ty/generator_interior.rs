@@ -0,0 +1,125 @@
+// Copyright 2017 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The data types describing a generator's interior: the set of types
+//! that must be saved across some suspend point, with enough liveness
+//! information attached for the MIR generator transform to coalesce
+//! non-overlapping slots, and enough span information for diagnostics.
+//! These live here, rather than in `librustc_typeck` where they are
+//! computed (see `rustc_typeck::check::generator_interior`), because
+//! they are stored in `ty::TypeckTables`, and `librustc_typeck` depends
+//! on `librustc`, not the other way around.
+
+use syntax_pos::Span;
+use ty::{Ty, TyCtxt};
+use util::nodemap::FxHashSet;
+
+/// A dense index assigned to each `yield` expression in a generator body.
+/// Two saved types whose liveness sets of indices are disjoint can never
+/// be live at the same suspend point, and so may share storage.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct YieldIndex(pub usize);
+
+/// The yield points that a saved interior type is live across.
+#[derive(Clone, Debug)]
+pub enum Liveness {
+    /// Live across exactly the yields recorded here.
+    Yields(FxHashSet<YieldIndex>),
+    /// No enclosing scope was found for this type (e.g. it has no
+    /// associated destruction scope), so it must conservatively be
+    /// treated as live across every yield in the body.
+    Always,
+}
+
+impl Liveness {
+    pub fn overlaps(&self, other: &Liveness) -> bool {
+        match (self, other) {
+            (&Liveness::Yields(ref a), &Liveness::Yields(ref b)) => !a.is_disjoint(b),
+            _ => true,
+        }
+    }
+
+    pub fn contains(&self, index: YieldIndex) -> bool {
+        match *self {
+            Liveness::Yields(ref set) => set.contains(&index),
+            Liveness::Always => true,
+        }
+    }
+
+    pub fn union(&mut self, other: Liveness) {
+        let merged = match (&*self, other) {
+            (&Liveness::Yields(ref a), Liveness::Yields(b)) => {
+                Liveness::Yields(a.union(&b).cloned().collect())
+            }
+            _ => Liveness::Always,
+        };
+        *self = merged;
+    }
+}
+
+/// The span of the value that causes an interior type to be live across a
+/// yield, and the span of the yield it is live across, used to build
+/// "value is held across this suspend point" notes on auto-trait errors.
+#[derive(Clone, Copy, Debug)]
+pub struct GeneratorInteriorTypeCause {
+    /// Where the value of this type was bound or created.
+    pub span: Span,
+    /// The `yield` expression it is live across.
+    pub yield_span: Span,
+}
+
+/// A type saved in a generator's state because it is live across some
+/// suspend point, together with the span that made it live and the set of
+/// yields it must be stored across.
+pub struct GeneratorSavedTy<'tcx> {
+    pub ty: Ty<'tcx>,
+    pub span: Span,
+    /// The first yield this type was found to be live across, if any
+    /// (types recorded with no enclosing scope have none).
+    pub yield_span: Option<Span>,
+    pub liveness: Liveness,
+}
+
+impl<'tcx> GeneratorSavedTy<'tcx> {
+    /// Whether the storage for `self` and `other` could be shared: they
+    /// may overlap in memory iff the sets of yields they are live across
+    /// are disjoint.
+    pub fn may_overlap(&self, other: &GeneratorSavedTy) -> bool {
+        !self.liveness.overlaps(&other.liveness)
+    }
+
+    /// Whether this type is live across the yield at `index`.
+    pub fn live_at(&self, index: YieldIndex) -> bool {
+        self.liveness.contains(index)
+    }
+}
+
+/// The interior of a generator, as computed by
+/// `rustc_typeck::check::generator_interior::resolve_interior`. Unlike a
+/// plain witness tuple, this retains per-type liveness information so
+/// that the MIR generator transform can do interference-based field
+/// coalescing instead of giving every saved value its own field.
+pub struct GeneratorInterior<'tcx> {
+    pub slots: Vec<GeneratorSavedTy<'tcx>>,
+    /// The number of yield points in the body this interior was computed
+    /// from; `YieldIndex`es recorded on `slots` are all `< num_yields`.
+    pub num_yields: usize,
+}
+
+impl<'tcx> GeneratorInterior<'tcx> {
+    /// The witness tuple used for auto-trait checking: one field per
+    /// distinct saved type, regardless of whether their live ranges
+    /// overlap.
+    pub fn witness(&self, tcx: TyCtxt<'_, 'tcx, 'tcx>) -> Ty<'tcx> {
+        let distinct: FxHashSet<_> = self.slots.iter().map(|slot| slot.ty).collect();
+        let types: Vec<_> = distinct.into_iter().collect();
+        tcx.intern_tup(&types, false)
+    }
+}
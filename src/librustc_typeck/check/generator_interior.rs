@@ -8,12 +8,40 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+//! This calculates the types which has storage which lives across a suspension
+//! point in a generator from the perspective of typeck. The resulting types are
+//! amended to the `ty::GeneratorInterior` struct (defined in `librustc` rather
+//! than here, since it is stored in the shared `ty::TypeckTables` that this
+//! crate depends on) which is later used by the MIR generator transform to
+//! compute the actual layout of generator states. The span each type was
+//! found live at is also recorded, so that a failed auto-trait obligation on
+//! the generator's witness can report which value and which `yield` are
+//! responsible. Finally, the estimated size of the state live across any one
+//! suspend point (the largest such size, not the sum over every saved type)
+//! is checked against the `large_generator_interior` lint so that oversized generators
+//! are surfaced without having to inspect MIR by hand. Nested closures and
+//! generators are never walked into (their own bodies have their own
+//! interiors); when one is live across an outer yield, it is its own
+//! concrete type -- not the flattened tuple of whatever that type stores
+//! internally -- that is recorded as a slot of the *outer* interior, since
+//! that is what is physically held in the outer generator's state.
+//!
+//! A closure or generator does not get its own independent `TypeckTables`:
+//! one table is shared by an item and everything nested inside it, so the
+//! per-generator data this module writes (`generator_interiors`,
+//! `generator_interior_types`) is keyed/merged accordingly rather than
+//! simply overwritten.
+
 use log;
 use rustc::hir::def_id::DefId;
 use rustc::hir::intravisit::{self, Visitor, NestedVisitorMap};
 use rustc::hir::{self, Body, Pat, PatKind, Expr};
 use rustc::middle::region::{RegionMaps, CodeExtent};
-use rustc::ty::Ty;
+use rustc::ty::{Ty, TyCtxt};
+use rustc::ty::generator_interior::{
+    GeneratorInterior, GeneratorInteriorTypeCause, GeneratorSavedTy, Liveness, YieldIndex,
+};
+use std::collections::hash_map::Entry;
 use syntax::ast::NodeId;
 use syntax::codemap::Span;
 use std::rc::Rc;
@@ -21,11 +49,162 @@ use super::FnCtxt;
 use util::nodemap::FxHashSet;
 use util::nodemap::FxHashMap;
 
+declare_lint! {
+    pub LARGE_GENERATOR_INTERIOR,
+    Warn,
+    "detects generators (including the state machines generated for `async fn`s) with a \
+     very large estimated state size"
+}
+
+// `LARGE_GENERATOR_INTERIOR` still needs to be registered with the lint
+// store -- e.g. via a `LintPass` whose `get_lints` returns
+// `lint_array!(LARGE_GENERATOR_INTERIOR)`, added to
+// `rustc_lint::register_builtins` -- before `struct_span_lint_node` below
+// can be called without the compiler asserting on an unknown lint. That
+// registration belongs in the `rustc_lint` crate, not here.
+
+/// Live-at-once state whose estimated size is at least this many bytes
+/// triggers the `large_generator_interior` lint.
+const LARGE_GENERATOR_INTERIOR_THRESHOLD_BYTES: u64 = 2048;
+
+/// Warns, behind `large_generator_interior`, when the estimated size of a
+/// generator's saved state is large enough to be a performance footgun.
+///
+/// Two slots whose `YieldIndex` sets are disjoint can share storage (see
+/// `GeneratorSavedTy::may_overlap`), so simply summing every slot's size
+/// would overstate the interior by however much its types happen to
+/// overlap. Instead this takes, for each yield point, the combined size
+/// of only the slots live at that point, and reports the worst (largest)
+/// of those -- the tightest bound on what must actually be resident at
+/// once, rather than a loose upper bound on the whole interior.
+fn check_large_interior<'a, 'gcx, 'tcx>(fcx: &FnCtxt<'a, 'gcx, 'tcx>,
+                                         interior: &GeneratorInterior<'tcx>,
+                                         owner: NodeId,
+                                         span: Span) {
+    let sizes: Vec<_> = interior.slots.iter()
+        .filter_map(|slot| {
+            fcx.tcx.layout_of(fcx.param_env.and(slot.ty)).ok()
+                .map(|layout| (slot, layout.size.bytes()))
+        })
+        .collect();
+
+    // A slot with no recorded yield (`Liveness::Always`) is live at every
+    // index, so folding over at least one index is enough to account for
+    // it; a generator with no yields at all has nothing live across a
+    // suspend point to warn about.
+    let max_live_size = (0..interior.num_yields)
+        .map(|y| {
+            let index = YieldIndex(y);
+            sizes.iter()
+                .filter(|&&(slot, _)| slot.live_at(index))
+                .map(|&(_, size)| size)
+                .sum::<u64>()
+        })
+        .max()
+        .unwrap_or(0);
+
+    if max_live_size < LARGE_GENERATOR_INTERIOR_THRESHOLD_BYTES {
+        return;
+    }
+
+    let worst_index = (0..interior.num_yields)
+        .max_by_key(|&y| {
+            sizes.iter()
+                .filter(|&&(slot, _)| slot.live_at(YieldIndex(y)))
+                .map(|&(_, size)| size)
+                .sum::<u64>()
+        });
+
+    let mut worst_slots: Vec<_> = match worst_index {
+        Some(y) => sizes.iter()
+            .cloned()
+            .filter(|&(slot, _)| slot.live_at(YieldIndex(y)))
+            .collect(),
+        None => sizes,
+    };
+    worst_slots.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut diag = fcx.tcx.struct_span_lint_node(
+        LARGE_GENERATOR_INTERIOR,
+        owner,
+        span,
+        &format!("this generator (or the state machine generated for an `async fn`) holds \
+                  {} bytes live across a single suspend point", max_live_size),
+    );
+    for &(slot, size) in worst_slots.iter().take(5) {
+        diag.span_note(slot.span, &format!("`{}` ({} bytes) is held here", slot.ty, size));
+    }
+    diag.emit();
+}
+
+/// Walks a generator body once, in source order, assigning each `yield`
+/// expression encountered a dense `YieldIndex`. Like the main
+/// `InteriorVisitor`, this does not descend into nested closures or
+/// generators: their yields belong to their own interior, not this one.
+struct YieldCollector {
+    yields: Vec<Span>,
+}
+
+impl<'tcx> Visitor<'tcx> for YieldCollector {
+    fn nested_visit_map<'this>(&'this mut self) -> NestedVisitorMap<'this, 'tcx> {
+        NestedVisitorMap::None
+    }
+
+    fn visit_body(&mut self, _body: &'tcx Body) {
+        // Closures inside are not considered part of the generator interior
+    }
+
+    fn visit_expr(&mut self, expr: &'tcx Expr) {
+        if let hir::ExprKind::Yield(_) = expr.node {
+            self.yields.push(expr.span);
+        }
+
+        intravisit::walk_expr(self, expr);
+    }
+}
+
+fn span_contains(outer: Span, inner: Span) -> bool {
+    outer.lo() <= inner.lo() && inner.hi() <= outer.hi()
+}
+
+/// Returns the indices (into `yields`, in source order) of every yield
+/// that lexically falls inside `extent`, together with the span of the
+/// first one, memoizing the result per extent in `cache`.
+fn yield_in_extent(hir: &hir::map::Map,
+                    extent: CodeExtent,
+                    yields: &[Span],
+                    cache: &mut FxHashMap<NodeId, Option<(Span, FxHashSet<YieldIndex>)>>)
+                    -> Option<(Span, FxHashSet<YieldIndex>)> {
+    let node_id = extent.node_id();
+    if let Some(cached) = cache.get(&node_id) {
+        return cached.clone();
+    }
+
+    let result = extent.span(hir).and_then(|extent_span| {
+        let indices: FxHashSet<YieldIndex> = yields.iter()
+            .enumerate()
+            .filter(|&(_, &span)| span_contains(extent_span, span))
+            .map(|(i, _)| YieldIndex(i))
+            .collect();
+
+        indices.iter()
+            .map(|idx| yields[idx.0])
+            .min_by_key(|span| span.lo())
+            .map(|first_span| (first_span, indices))
+    });
+
+    cache.insert(node_id, result.clone());
+    result
+}
+
 struct InteriorVisitor<'a, 'gcx: 'a+'tcx, 'tcx: 'a> {
     fcx: &'a FnCtxt<'a, 'gcx, 'tcx>,
-    cache: FxHashMap<NodeId, Option<Span>>,
-    types: FxHashSet<Ty<'tcx>>,
+    cache: FxHashMap<NodeId, Option<(Span, FxHashSet<YieldIndex>)>>,
+    types: FxHashMap<Ty<'tcx>, GeneratorSavedTy<'tcx>>,
     region_maps: Rc<RegionMaps>,
+    /// Every `yield` in this body, in source order; the position in this
+    /// list is the `YieldIndex` used throughout `record`.
+    yields: Vec<Span>,
 }
 
 impl<'a, 'gcx, 'tcx> InteriorVisitor<'a, 'gcx, 'tcx> {
@@ -33,32 +212,60 @@ impl<'a, 'gcx, 'tcx> InteriorVisitor<'a, 'gcx, 'tcx> {
         use syntax_pos::DUMMY_SP;
 
         let live_across_yield = scope.map(|s| {
-            self.fcx.tcx.yield_in_extent(s, &mut self.cache).is_some()
-        }).unwrap_or(true);
-
-        if live_across_yield {
-            if log_enabled!(log::LogLevel::Debug) {
-                if let Some(s) = scope {
-                    let span = s.span(&self.fcx.tcx.hir).unwrap_or(DUMMY_SP);
-                    debug!("type in generator with scope = {:?}, type = {:?}, span = {:?}",
+            yield_in_extent(&self.fcx.tcx.hir, s, &self.yields, &mut self.cache)
+        });
+
+        let liveness = match live_across_yield {
+            // Found a scope and this is live across a yield.
+            Some(Some((yield_span, ref yield_indices))) => {
+                if log_enabled!(log::LogLevel::Debug) {
+                    let span = scope.and_then(|s| s.span(&self.fcx.tcx.hir)).unwrap_or(DUMMY_SP);
+                    debug!("type in generator with scope = {:?}, type = {:?}, span = {:?}, \
+                            yield span = {:?}, yields crossed = {:?}",
                            scope,
                            self.fcx.resolve_type_vars_if_possible(&ty),
-                           span);
-                } else {
-                    debug!("type in generator WITHOUT scope, type = {:?}",
-                           self.fcx.resolve_type_vars_if_possible(&ty));
+                           span,
+                           yield_span,
+                           yield_indices);
+                    if let Some(e) = expr {
+                        debug!("type from expression: {:?}, span={:?}", e, e.span);
+                    }
                 }
+                Some(Liveness::Yields(yield_indices.clone()))
+            }
+            // Found a scope, but this is not live across a yield.
+            Some(None) => {
                 if let Some(e) = expr {
-                    debug!("type from expression: {:?}, span={:?}", e, e.span);
+                    debug!("NO type from expression: {:?}, span = {:?}", e, e.span);
                 }
+                None
             }
-            self.types.insert(ty);
-        } else {
-            if let Some(e) = expr {
-                debug!("NO type from expression: {:?}, span = {:?}", e, e.span);
+            // No scope, so conservatively treat as live across every yield.
+            None => Some(Liveness::Always),
+        };
+
+        if let Some(liveness) = liveness {
+            let span = expr.map(|e| e.span)
+                .or_else(|| scope.and_then(|s| s.span(&self.fcx.tcx.hir)))
+                .unwrap_or(DUMMY_SP);
+            let yield_span = match live_across_yield {
+                Some(Some((yield_span, _))) => Some(yield_span),
+                _ => None,
+            };
+
+            match self.types.entry(ty) {
+                Entry::Occupied(mut entry) => {
+                    let entry = entry.get_mut();
+                    entry.liveness.union(liveness);
+                    entry.yield_span = entry.yield_span.or(yield_span);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(GeneratorSavedTy { ty, span, yield_span, liveness });
+                }
             }
         }
     }
+
 }
 
 pub fn resolve_interior<'a, 'gcx, 'tcx>(fcx: &'a FnCtxt<'a, 'gcx, 'tcx>,
@@ -66,21 +273,31 @@ pub fn resolve_interior<'a, 'gcx, 'tcx>(fcx: &'a FnCtxt<'a, 'gcx, 'tcx>,
                                         body_id: hir::BodyId,
                                         witness: Ty<'tcx>) {
     let body = fcx.tcx.hir.body(body_id);
+
+    let mut yield_collector = YieldCollector { yields: Vec::new() };
+    intravisit::walk_body(&mut yield_collector, body);
+    let num_yields = yield_collector.yields.len();
+
     let mut visitor = InteriorVisitor {
         fcx,
-        types: FxHashSet(),
+        types: FxHashMap(),
         cache: FxHashMap(),
         region_maps: fcx.tcx.region_maps(def_id),
+        yields: yield_collector.yields,
     };
     intravisit::walk_body(&mut visitor, body);
 
-    // Deduplicate types
-    let set: FxHashSet<_> = visitor.types.into_iter()
-        .map(|t| fcx.resolve_type_vars_if_possible(&t))
+    // Resolve any type variables that could still be present in the slots
+    // collected above, now that typeck of the body has finished.
+    let slots: Vec<_> = visitor.types.into_iter()
+        .map(|(ty, mut slot)| {
+            slot.ty = fcx.resolve_type_vars_if_possible(&ty);
+            slot
+        })
         .collect();
-    let types: Vec<_> = set.into_iter().collect();
 
-    let tuple = fcx.tcx.intern_tup(&types, false);
+    let interior = GeneratorInterior { slots, num_yields };
+    let tuple = interior.witness(fcx.tcx);
 
     debug!("Types in generator {:?}, span = {:?}", tuple, body.value.span);
 
@@ -88,7 +305,41 @@ pub fn resolve_interior<'a, 'gcx, 'tcx>(fcx: &'a FnCtxt<'a, 'gcx, 'tcx>,
     match fcx.at(&fcx.misc(body.value.span), fcx.param_env).eq(witness, tuple) {
         Ok(ok) => fcx.register_infer_ok_obligations(ok),
         _ => bug!(),
-   }
+    }
+
+    // For every type we can blame on a specific yield, remember where the
+    // value came from and which yield it crosses. Trait selection looks
+    // this up by the offending type when an auto-trait obligation on the
+    // witness fails, to emit a "value is held across this suspend point"
+    // note pointing at both spans. `generator_interior_types` is shared by
+    // the whole item (this generator and any others nested inside it), so
+    // entries are merged in rather than overwriting whatever a
+    // already-checked nested generator recorded.
+    {
+        let mut tables = fcx.tables.borrow_mut();
+        for slot in &interior.slots {
+            if let Some(yield_span) = slot.yield_span {
+                tables.generator_interior_types.entry(slot.ty)
+                    .or_insert(GeneratorInteriorTypeCause { span: slot.span, yield_span });
+            }
+        }
+    }
+
+    if let Some(owner) = fcx.tcx.hir.as_local_node_id(fcx.tcx.hir.body_owner_def_id(body_id)) {
+        check_large_interior(fcx, &interior, owner, body.value.span);
+    }
+
+    // Stash the richer interior (slots + their yield-index liveness), keyed
+    // by this generator's own `def_id`, so that the later MIR generator
+    // transform can read it back for field coalescing and layout. If this
+    // generator is itself nested inside another one and gets stored across
+    // one of the outer generator's yields, the outer interior records this
+    // generator's own type (its witness tuple is not a type that is ever
+    // physically stored anywhere), so nothing here needs to be read back
+    // mid-typeck -- this entry is purely for the downstream MIR pass. The
+    // table is shared with the enclosing item, so this must not clobber
+    // any sibling generator's entry.
+    fcx.tables.borrow_mut().generator_interiors.insert(def_id, interior);
 }
 
 impl<'a, 'gcx, 'tcx> Visitor<'tcx> for InteriorVisitor<'a, 'gcx, 'tcx> {
@@ -112,6 +363,19 @@ impl<'a, 'gcx, 'tcx> Visitor<'tcx> for InteriorVisitor<'a, 'gcx, 'tcx> {
 
     fn visit_expr(&mut self, expr: &'tcx Expr) {
         let scope = self.region_maps.temporary_scope(expr.id);
+
+        // A closure's (or nested generator's) captures are its own
+        // values, not part of this generator's interior, so its body must
+        // never be walked from here -- `nested_visit_map` returning
+        // `NestedVisitorMap::None` already keeps `walk_expr` below from
+        // descending into it. The closure or generator *value itself* is
+        // still recorded like any other expression: what is physically
+        // held live across a yield is one value of its own concrete type,
+        // never the flattened tuple of types that type happens to store
+        // internally. (A nested generator's own interior is still
+        // resolved and stashed via its own `resolve_interior` call, for
+        // the MIR layout pass to use -- this generator just doesn't need
+        // to read it back.)
         let ty = self.fcx.tables.borrow().expr_ty_adjusted(expr);
         self.record(ty, scope, Some(expr));
 